@@ -2,11 +2,34 @@ use crate::ast::Call;
 use crate::engine::Command;
 use crate::engine::EvaluationContext;
 use crate::BlockId;
+use crate::ShellError;
+use crate::Span;
 use crate::SyntaxShape;
 use crate::Value;
 use crate::VarId;
 
+/// Target shell for `Signature::generate_completion`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// A named group of arguments where, when `required` is true, at least one
+/// member must be present on the command line. More than one member being
+/// present is not rejected, and a group with `required` false is not checked.
+/// Modeled after clap's `ArgGroup`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgGroup {
+    pub name: String,
+    pub args: Vec<String>,
+    pub required: bool,
+}
+
+// `Eq` is intentionally not derived: `default` may hold a `Value::Float`, which is
+// not `Eq`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Flag {
     pub long: String,
     pub short: Option<char>,
@@ -15,15 +38,25 @@ pub struct Flag {
     pub desc: String,
     // For custom commands
     pub var_id: Option<VarId>,
+    // Value to bind when the flag is absent from the call
+    pub default: Option<Value>,
+    // Closed set of allowed values; empty means unconstrained
+    pub possible_values: Vec<String>,
+    // Visible and bindable on any subcommand in the same invocation chain
+    pub global: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PositionalArg {
     pub name: String,
     pub desc: String,
     pub shape: SyntaxShape,
     // For custom commands
     pub var_id: Option<VarId>,
+    // Value to bind when the positional is absent from the call
+    pub default: Option<Value>,
+    // Closed set of allowed values; empty means unconstrained
+    pub possible_values: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -34,8 +67,17 @@ pub struct Signature {
     pub required_positional: Vec<PositionalArg>,
     pub optional_positional: Vec<PositionalArg>,
     pub rest_positional: Option<PositionalArg>,
+    // A positional that only collects arguments following a literal `--`, raw and
+    // unparsed, skipping any intervening optional positionals
+    pub last_positional: Option<PositionalArg>,
     pub named: Vec<Flag>,
     pub is_filter: bool,
+    // Mutual-exclusion and dependency constraints between flags/positionals, keyed
+    // by a flag's long name or a positional's name. Validated against a bound
+    // `Call` after parsing.
+    pub conflicts: Vec<(String, String)>,
+    pub requires: Vec<(String, String)>,
+    pub groups: Vec<ArgGroup>,
 }
 
 impl PartialEq for Signature {
@@ -45,6 +87,7 @@ impl PartialEq for Signature {
             && self.required_positional == other.required_positional
             && self.optional_positional == other.optional_positional
             && self.rest_positional == other.rest_positional
+            && self.last_positional == other.last_positional
             && self.is_filter == other.is_filter
     }
 }
@@ -60,8 +103,12 @@ impl Signature {
             required_positional: vec![],
             optional_positional: vec![],
             rest_positional: None,
+            last_positional: None,
             named: vec![],
             is_filter: false,
+            conflicts: vec![],
+            requires: vec![],
+            groups: vec![],
         }
     }
     pub fn build(name: impl Into<String>) -> Signature {
@@ -86,6 +133,8 @@ impl Signature {
             desc: desc.into(),
             shape: shape.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
         });
 
         self
@@ -103,17 +152,94 @@ impl Signature {
             desc: desc.into(),
             shape: shape.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
         });
 
         self
     }
 
     pub fn rest(mut self, shape: impl Into<SyntaxShape>, desc: impl Into<String>) -> Signature {
+        debug_assert!(
+            self.last_positional.is_none(),
+            "rest and rest_last are mutually exclusive positionals"
+        );
+
         self.rest_positional = Some(PositionalArg {
             name: "rest".into(),
             desc: desc.into(),
             shape: shape.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
+        });
+
+        self
+    }
+
+    /// Add a positional that only collects the arguments following a literal `--`,
+    /// e.g. `run <script> -- <args passed verbatim>`. The parser stops interpreting
+    /// flags at `--` and feeds everything after it to this positional, bypassing any
+    /// optional positionals that would otherwise claim those words.
+    pub fn rest_last(
+        mut self,
+        name: impl Into<String>,
+        shape: impl Into<SyntaxShape>,
+        desc: impl Into<String>,
+    ) -> Signature {
+        debug_assert!(
+            self.rest_positional.is_none(),
+            "rest and rest_last are mutually exclusive positionals"
+        );
+
+        self.last_positional = Some(PositionalArg {
+            name: name.into(),
+            desc: desc.into(),
+            shape: shape.into(),
+            var_id: None,
+            default: None,
+            possible_values: vec![],
+        });
+
+        self
+    }
+
+    /// Add an optional positional argument with a default value used when the
+    /// positional is omitted
+    pub fn optional_default(
+        mut self,
+        name: impl Into<String>,
+        shape: impl Into<SyntaxShape>,
+        desc: impl Into<String>,
+        default: Value,
+    ) -> Signature {
+        self.optional_positional.push(PositionalArg {
+            name: name.into(),
+            desc: desc.into(),
+            shape: shape.into(),
+            var_id: None,
+            default: Some(default),
+            possible_values: vec![],
+        });
+
+        self
+    }
+
+    /// Add a required positional argument constrained to a closed set of string
+    /// values, e.g. `required_choice("direction", &["asc", "desc"], "sort order")`
+    pub fn required_choice(
+        mut self,
+        name: impl Into<String>,
+        choices: &[&str],
+        desc: impl Into<String>,
+    ) -> Signature {
+        self.required_positional.push(PositionalArg {
+            name: name.into(),
+            desc: desc.into(),
+            shape: SyntaxShape::String,
+            var_id: None,
+            default: None,
+            possible_values: choices.iter().map(|s| (*s).to_string()).collect(),
         });
 
         self
@@ -136,6 +262,62 @@ impl Signature {
             required: false,
             desc: desc.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
+            global: false,
+        });
+
+        self
+    }
+
+    /// Add an optional named flag argument constrained to a closed set of string
+    /// values, e.g. `named_choice("format", &["json", "yaml"], "output format", None)`
+    pub fn named_choice(
+        mut self,
+        name: impl Into<String>,
+        choices: &[&str],
+        desc: impl Into<String>,
+        short: Option<char>,
+    ) -> Signature {
+        let (name, s) = self.check_names(name, short);
+
+        self.named.push(Flag {
+            long: name,
+            short: s,
+            arg: Some(SyntaxShape::String),
+            required: false,
+            desc: desc.into(),
+            var_id: None,
+            default: None,
+            possible_values: choices.iter().map(|s| (*s).to_string()).collect(),
+            global: false,
+        });
+
+        self
+    }
+
+    /// Add an optional named flag argument with a default value used when the
+    /// flag is omitted
+    pub fn named_default(
+        mut self,
+        name: impl Into<String>,
+        shape: impl Into<SyntaxShape>,
+        desc: impl Into<String>,
+        short: Option<char>,
+        default: Value,
+    ) -> Signature {
+        let (name, s) = self.check_names(name, short);
+
+        self.named.push(Flag {
+            long: name,
+            short: s,
+            arg: Some(shape.into()),
+            required: false,
+            desc: desc.into(),
+            var_id: None,
+            default: Some(default),
+            possible_values: vec![],
+            global: false,
         });
 
         self
@@ -158,6 +340,9 @@ impl Signature {
             required: true,
             desc: desc.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
+            global: false,
         });
 
         self
@@ -179,11 +364,98 @@ impl Signature {
             required: false,
             desc: desc.into(),
             var_id: None,
+            default: None,
+            possible_values: vec![],
+            global: false,
+        });
+
+        self
+    }
+
+    /// Add a named flag argument that is visible and bindable on any subcommand in
+    /// the same invocation chain, e.g. `git --verbose subcommand`
+    pub fn global_named(
+        mut self,
+        name: impl Into<String>,
+        shape: impl Into<SyntaxShape>,
+        desc: impl Into<String>,
+        short: Option<char>,
+    ) -> Signature {
+        let (name, s) = self.check_names(name, short);
+
+        self.named.push(Flag {
+            long: name,
+            short: s,
+            arg: Some(shape.into()),
+            required: false,
+            desc: desc.into(),
+            var_id: None,
+            default: None,
+            possible_values: vec![],
+            global: true,
+        });
+
+        self
+    }
+
+    /// Add a switch that is visible and bindable on any subcommand in the same
+    /// invocation chain, e.g. `git --verbose subcommand`
+    pub fn global_switch(
+        mut self,
+        name: impl Into<String>,
+        desc: impl Into<String>,
+        short: Option<char>,
+    ) -> Signature {
+        let (name, s) = self.check_names(name, short);
+
+        self.named.push(Flag {
+            long: name,
+            short: s,
+            arg: None,
+            required: false,
+            desc: desc.into(),
+            var_id: None,
+            default: None,
+            possible_values: vec![],
+            global: true,
         });
 
         self
     }
 
+    /// The subset of `named` that are declared `global`
+    pub fn get_global_flags(&self) -> Vec<&Flag> {
+        self.named.iter().filter(|f| f.global).collect()
+    }
+
+    /// Merge in any global flags declared on `ancestor` that this signature
+    /// doesn't already declare itself, so a subcommand's `Call` can bind flags
+    /// inherited from a parent command's signature. The engine's command
+    /// resolution code is expected to call this for each ancestor in the
+    /// invocation chain before binding a subcommand's `Call`.
+    pub fn merge_global_flags(&mut self, ancestor: &Signature) {
+        for flag in ancestor.get_global_flags() {
+            let long_taken = self.named.iter().any(|f| f.long == flag.long);
+            if long_taken {
+                continue;
+            }
+
+            let mut flag = flag.clone();
+            let short_taken = flag
+                .short
+                .map(|s| self.get_shorts().contains(&s))
+                .unwrap_or(false);
+
+            // Only the short form collides: still inherit the flag under its long
+            // name, just without the short that the subcommand already claimed.
+            if short_taken {
+                flag.short = None;
+            }
+
+            self.named.push(flag);
+        }
+    }
+
     /// Get list of the short-hand flags
     pub fn get_shorts(&self) -> Vec<char> {
         self.named.iter().filter_map(|f| f.short).collect()
@@ -224,11 +496,18 @@ impl Signature {
             self.optional_positional
                 .get(position - self.required_positional.len())
                 .cloned()
-        } else {
+        } else if self.rest_positional.is_some() {
             self.rest_positional.clone()
+        } else {
+            self.last_positional.clone()
         }
     }
 
+    /// Whether this signature has a `--`-addressed "last" positional
+    pub fn has_last_positional(&self) -> bool {
+        self.last_positional.is_some()
+    }
+
     pub fn num_positionals(&self) -> usize {
         let mut total = self.required_positional.len() + self.optional_positional.len();
 
@@ -244,6 +523,11 @@ impl Signature {
                 total += 1;
             }
         }
+        if self.last_positional.is_some() {
+            // The `--`-addressed positional is a real bindable slot past the
+            // separator
+            total += 1;
+        }
         total
     }
 
@@ -265,6 +549,10 @@ impl Signature {
                 }
             }
         }
+        if self.last_positional.is_some() {
+            // Always past any index within required_positional
+            total += 1;
+        }
         total
     }
 
@@ -296,6 +584,179 @@ impl Signature {
         self
     }
 
+    /// Declare that `a` and `b` cannot both be given at the same time
+    pub fn conflicts(mut self, a: impl Into<String>, b: impl Into<String>) -> Signature {
+        self.conflicts.push((a.into(), b.into()));
+        self
+    }
+
+    /// Declare that if `a` is given, `b` must also be given
+    pub fn requires(mut self, a: impl Into<String>, b: impl Into<String>) -> Signature {
+        self.requires.push((a.into(), b.into()));
+        self
+    }
+
+    /// Declare an argument group: when `required` is true, at least one member of
+    /// `members` must be present on the command line
+    pub fn group(mut self, name: impl Into<String>, members: &[&str], required: bool) -> Signature {
+        self.groups.push(ArgGroup {
+            name: name.into(),
+            args: members.iter().map(|s| (*s).to_string()).collect(),
+            required,
+        });
+        self
+    }
+
+    /// The index a required/optional positional named `name` would occupy in
+    /// `Call::positional`, if any
+    fn position_index(&self, name: &str) -> Option<usize> {
+        self.required_positional
+            .iter()
+            .position(|p| p.name == name)
+            .or_else(|| {
+                self.optional_positional
+                    .iter()
+                    .position(|p| p.name == name)
+                    .map(|i| i + self.required_positional.len())
+            })
+    }
+
+    /// Whether `name` (a flag's long name or a positional's name) was given on
+    /// `call`
+    fn has_arg(&self, call: &Call, name: &str) -> bool {
+        call.has_flag(name)
+            || self
+                .position_index(name)
+                .map(|idx| call.positional.get(idx).is_some())
+                .unwrap_or(false)
+    }
+
+    /// Validate a bound `Call` against the conflicts/requires/group constraints
+    /// declared on this signature, returning a `ShellError` for the first
+    /// violation found.
+    pub fn check_constraints(&self, call: &Call) -> Result<(), ShellError> {
+        for (a, b) in &self.conflicts {
+            if self.has_arg(call, a) && self.has_arg(call, b) {
+                return Err(ShellError::IncompatibleParametersSingle(
+                    format!("`{}` cannot be used with `{}`", a, b),
+                    call.head,
+                ));
+            }
+        }
+
+        for (a, b) in &self.requires {
+            if self.has_arg(call, a) && !self.has_arg(call, b) {
+                return Err(ShellError::IncompatibleParametersSingle(
+                    format!("`{}` requires `{}`", a, b),
+                    call.head,
+                ));
+            }
+        }
+
+        for group in &self.groups {
+            let present = group.args.iter().filter(|a| self.has_arg(call, a)).count();
+            if group.required && present == 0 {
+                return Err(ShellError::IncompatibleParametersSingle(
+                    format!("one of [{}] is required", group.args.join(", ")),
+                    call.head,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate `value` against the possible-values set declared for the flag or
+    /// positional named `name`, returning a `ShellError` (with a nearest-match
+    /// suggestion when one exists) if it isn't one of the allowed choices. Names
+    /// with no declared choices are unconstrained and always pass.
+    pub fn validate_choice(&self, name: &str, value: &str, span: Span) -> Result<(), ShellError> {
+        let choices = self
+            .named
+            .iter()
+            .find(|f| f.long == name)
+            .map(|f| &f.possible_values)
+            .or_else(|| {
+                self.required_positional
+                    .iter()
+                    .chain(self.optional_positional.iter())
+                    .find(|p| p.name == name)
+                    .map(|p| &p.possible_values)
+            });
+
+        let choices = match choices {
+            Some(choices) if !choices.is_empty() => choices,
+            _ => return Ok(()),
+        };
+
+        if choices.iter().any(|choice| choice == value) {
+            return Ok(());
+        }
+
+        let expected = choices.join(", ");
+        let msg = match nearest_choice(value, choices) {
+            Some(suggestion) => format!(
+                "`{}` isn't a valid value for `{}`, expected one of: {} (did you mean `{}`?)",
+                value, name, expected, suggestion
+            ),
+            None => format!(
+                "`{}` isn't a valid value for `{}`, expected one of: {}",
+                value, name, expected
+            ),
+        };
+
+        Err(ShellError::TypeMismatch(msg, span))
+    }
+
+    /// Generate a completion script for `shell` that offers this signature's long
+    /// and short flags, plus value completions from the closed choice sets of its
+    /// positionals and filename completion where a `SyntaxShape::Filepath` is
+    /// known. Choice sets declared on named flags are not offered.
+    pub fn generate_completion(&self, shell: Shell) -> String {
+        let prog = self.name.replace(' ', "_");
+
+        let long_flags: Vec<String> = self.named.iter().map(|f| format!("--{}", f.long)).collect();
+        let short_flags: Vec<String> = self
+            .named
+            .iter()
+            .filter_map(|f| f.short)
+            .map(|c| format!("-{}", c))
+            .collect();
+
+        let mut values = vec![];
+        for positional in self
+            .required_positional
+            .iter()
+            .chain(self.optional_positional.iter())
+            .chain(self.rest_positional.iter())
+        {
+            values.extend(positional.possible_values.iter().cloned());
+        }
+
+        let has_filepath = self
+            .named
+            .iter()
+            .any(|f| matches!(f.arg, Some(SyntaxShape::Filepath)))
+            || self
+                .required_positional
+                .iter()
+                .chain(self.optional_positional.iter())
+                .chain(self.rest_positional.iter())
+                .any(|p| matches!(p.shape, SyntaxShape::Filepath));
+
+        match shell {
+            Shell::Bash => {
+                generate_bash_completion(&prog, &long_flags, &short_flags, &values, has_filepath)
+            }
+            Shell::Zsh => {
+                generate_zsh_completion(&prog, &long_flags, &short_flags, &values, has_filepath)
+            }
+            Shell::Fish => {
+                generate_fish_completion(&prog, &long_flags, &short_flags, &values, has_filepath)
+            }
+        }
+    }
+
     /// Create a placeholder implementation of Command as a way to predeclare a definition's
     /// signature so other definitions can see it. This placeholder is later replaced with the
     /// full definition in a second pass of the parser.
@@ -312,6 +773,120 @@ impl Signature {
     }
 }
 
+/// Find the closest possible value to `value` by edit distance, for use in "did
+/// you mean" hints. Returns `None` if nothing is reasonably close.
+fn nearest_choice(value: &str, choices: &[String]) -> Option<String> {
+    choices
+        .iter()
+        .map(|choice| (choice, levenshtein_distance(value, choice)))
+        .min_by_key(|(_, distance)| *distance)
+        .filter(|(_, distance)| *distance <= 3)
+        .map(|(choice, _)| choice.clone())
+}
+
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut prev = row[0];
+        row[0] = i;
+        for j in 1..=b.len() {
+            let cur = row[j];
+            row[j] = if a[i - 1] == b[j - 1] {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(row[j - 1])
+            };
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
+
+fn generate_bash_completion(
+    prog: &str,
+    long: &[String],
+    short: &[String],
+    values: &[String],
+    has_filepath: bool,
+) -> String {
+    let mut words: Vec<&str> = long
+        .iter()
+        .chain(short.iter())
+        .map(String::as_str)
+        .collect();
+    words.extend(values.iter().map(String::as_str));
+    let filepath_line = if has_filepath {
+        "    compopt -o filenames 2>/dev/null\n"
+    } else {
+        ""
+    };
+
+    format!(
+        "_{prog}() {{\n    local cur\n    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n{filepath}    COMPREPLY=( $(compgen -W \"{words}\" -- \"$cur\") )\n}}\ncomplete -F _{prog} {prog}\n",
+        prog = prog,
+        filepath = filepath_line,
+        words = words.join(" "),
+    )
+}
+
+fn generate_zsh_completion(
+    prog: &str,
+    long: &[String],
+    short: &[String],
+    values: &[String],
+    has_filepath: bool,
+) -> String {
+    let mut specs: Vec<String> = long.iter().map(|f| format!("'{}[]'", f)).collect();
+    specs.extend(short.iter().map(|f| format!("'{}[]'", f)));
+    if !values.is_empty() {
+        specs.push(format!("'*:value:({})'", values.join(" ")));
+    }
+    if has_filepath {
+        specs.push("'*:filename:_files'".into());
+    }
+
+    format!(
+        "#compdef {prog}\n_arguments \\\n  {specs}\n",
+        specs = specs.join(" \\\n  ")
+    )
+}
+
+fn generate_fish_completion(
+    prog: &str,
+    long: &[String],
+    short: &[String],
+    values: &[String],
+    has_filepath: bool,
+) -> String {
+    let mut lines = vec![];
+    for flag in long {
+        lines.push(format!(
+            "complete -c {} -l {}",
+            prog,
+            flag.trim_start_matches("--")
+        ));
+    }
+    for flag in short {
+        lines.push(format!(
+            "complete -c {} -s {}",
+            prog,
+            flag.trim_start_matches('-')
+        ));
+    }
+    if !values.is_empty() {
+        lines.push(format!("complete -c {} -a \"{}\"", prog, values.join(" ")));
+    }
+    if has_filepath {
+        lines.push(format!("complete -c {} -r -F", prog));
+    }
+
+    lines.join("\n") + "\n"
+}
+
 struct Predeclaration {
     signature: Signature,
 }
@@ -370,3 +945,183 @@ impl Command for BlockCommand {
         Some(self.block_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_choice_validates_flag_and_positional_values() {
+        let sig = Signature::new("sort")
+            .required_choice("direction", &["asc", "desc"], "sort order")
+            .named_choice("format", &["json", "yaml"], "output format", None);
+
+        assert!(sig
+            .validate_choice("direction", "asc", Span::unknown())
+            .is_ok());
+        assert!(sig
+            .validate_choice("format", "json", Span::unknown())
+            .is_ok());
+        assert!(sig
+            .validate_choice("format", "xml", Span::unknown())
+            .is_err());
+        // Names with no declared choices are unconstrained
+        assert!(sig
+            .validate_choice("unrelated", "anything", Span::unknown())
+            .is_ok());
+    }
+
+    #[test]
+    fn position_index_finds_required_and_optional_positionals() {
+        let sig = Signature::new("test")
+            .required("src", SyntaxShape::String, "source")
+            .optional("dst", SyntaxShape::String, "destination");
+
+        assert_eq!(sig.position_index("src"), Some(0));
+        assert_eq!(sig.position_index("dst"), Some(1));
+        assert_eq!(sig.position_index("missing"), None);
+    }
+
+    fn call_with_flags(flags: &[&str]) -> Call {
+        let mut call = Call::new();
+        for flag in flags {
+            call.named.push(((*flag).to_string(), None));
+        }
+        call
+    }
+
+    #[test]
+    fn check_constraints_rejects_a_fired_conflict() {
+        let sig = Signature::new("test")
+            .switch("all", "every item", None)
+            .switch("name", "by name", None)
+            .conflicts("all", "name");
+
+        assert!(sig
+            .check_constraints(&call_with_flags(&["all", "name"]))
+            .is_err());
+    }
+
+    #[test]
+    fn check_constraints_rejects_a_broken_requires() {
+        let sig = Signature::new("test")
+            .switch("follow", "follow symlinks", None)
+            .switch("recursive", "recurse into dirs", None)
+            .requires("follow", "recursive");
+
+        assert!(sig
+            .check_constraints(&call_with_flags(&["follow"]))
+            .is_err());
+    }
+
+    #[test]
+    fn check_constraints_rejects_an_empty_required_group() {
+        let sig = Signature::new("test")
+            .switch("json", "json output", None)
+            .switch("yaml", "yaml output", None)
+            .group("format", &["json", "yaml"], true);
+
+        assert!(sig.check_constraints(&call_with_flags(&[])).is_err());
+    }
+
+    #[test]
+    fn check_constraints_accepts_a_valid_call() {
+        let sig = Signature::new("test")
+            .switch("all", "every item", None)
+            .switch("name", "by name", None)
+            .conflicts("all", "name");
+
+        assert!(sig.check_constraints(&call_with_flags(&["all"])).is_ok());
+    }
+
+    #[test]
+    fn rest_last_is_counted_and_reachable() {
+        let sig = Signature::new("run")
+            .required("script", SyntaxShape::String, "script to run")
+            .rest_last("args", SyntaxShape::String, "args passed verbatim");
+
+        assert_eq!(sig.num_positionals(), 2);
+        assert_eq!(sig.num_positionals_after(0), 1);
+        assert_eq!(sig.get_positional(1).map(|p| p.name), Some("args".into()));
+    }
+
+    #[test]
+    fn optional_default_lands_in_optional_positionals() {
+        let sig = Signature::new("test").optional_default(
+            "depth",
+            SyntaxShape::Int,
+            "how deep to go",
+            Value::Int {
+                val: 1,
+                span: Span::unknown(),
+            },
+        );
+
+        assert!(sig.required_positional.is_empty());
+        assert_eq!(sig.optional_positional.len(), 1);
+        assert_eq!(sig.optional_positional[0].name, "depth");
+        assert!(sig.optional_positional[0].default.is_some());
+    }
+
+    #[test]
+    fn generate_completion_offers_flags_and_choice_values() {
+        let sig = Signature::new("sort")
+            .switch("reverse", "reverse the sort", Some('r'))
+            .required_choice("direction", &["asc", "desc"], "sort order");
+
+        let bash = sig.generate_completion(Shell::Bash);
+        assert!(bash.contains("--reverse"));
+        assert!(bash.contains("-r"));
+        assert!(bash.contains("asc"));
+
+        let zsh = sig.generate_completion(Shell::Zsh);
+        assert!(zsh.starts_with("#compdef sort"));
+
+        let fish = sig.generate_completion(Shell::Fish);
+        assert!(fish.contains("complete -c sort -l reverse"));
+        assert!(fish.contains("complete -c sort -s r"));
+    }
+
+    #[test]
+    fn merge_global_flags_inherits_without_clobbering_local_flags() {
+        let parent = Signature::new("git").global_switch("verbose", "be verbose", Some('v'));
+        let mut child = Signature::new("git commit").switch("amend", "amend the last commit", None);
+
+        child.merge_global_flags(&parent);
+
+        assert!(child.get_long_flag("verbose").is_some());
+        assert!(child.get_long_flag("amend").is_some());
+
+        // A second merge (e.g. re-resolving the same chain) must not duplicate
+        assert_eq!(
+            child.named.iter().filter(|f| f.long == "verbose").count(),
+            1
+        );
+        child.merge_global_flags(&parent);
+        assert_eq!(
+            child.named.iter().filter(|f| f.long == "verbose").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn merge_global_flags_does_not_clobber_a_colliding_short_flag() {
+        let parent = Signature::new("git").global_switch("verbose", "be verbose", Some('v'));
+        let mut child = Signature::new("git commit").named(
+            "value",
+            SyntaxShape::String,
+            "an unrelated flag",
+            Some('v'),
+        );
+
+        child.merge_global_flags(&parent);
+
+        // The subcommand's own `-v` must keep meaning `--value`, not silently
+        // become ambiguous with the inherited `--verbose`...
+        assert_eq!(child.get_short_flag('v').unwrap().long, "value");
+        // ...but `--verbose` should still be reachable by its long name, just
+        // without a short form
+        let verbose = child.get_long_flag("verbose").unwrap();
+        assert_eq!(verbose.short, None);
+    }
+}